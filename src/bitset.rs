@@ -0,0 +1,31 @@
+use std::collections::BTreeSet;
+
+/// A minimal stand-in for `std::collections::BitSet`, which hasn't
+/// existed in `std` since before Rust 1.0. This crate only ever needed
+/// insert/remove/contains/iterate-in-order over a sparse set of
+/// `usize`s, so a `BTreeSet` gives the same semantics without depending
+/// on an external crate (this tree has no `Cargo.toml` to add one to).
+#[derive(Default)]
+pub struct BitSet(BTreeSet<usize>);
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet(BTreeSet::new())
+    }
+
+    pub fn insert(&mut self, value: usize) -> bool {
+        self.0.insert(value)
+    }
+
+    pub fn remove(&mut self, value: &usize) -> bool {
+        self.0.remove(value)
+    }
+
+    pub fn contains(&self, value: &usize) -> bool {
+        self.0.contains(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().cloned()
+    }
+}
@@ -0,0 +1,255 @@
+use std::slice;
+use std::sync::atomic::Ordering;
+
+use super::bitset::BitSet;
+use super::{Index, INSTANCE_ID};
+
+/// A densely packed sibling of `IndexedVec`.
+///
+/// Where `IndexedVec` leaves a hole in its backing array every time an
+/// element is removed, `DenseIndexedVec` keeps its live elements
+/// contiguous in memory.  It does this by handing out stable `Index`
+/// tokens that refer to a *slot*, and internally mapping each slot to
+/// wherever the corresponding element currently lives in the packed
+/// `data` array -- so a `take`/`remove` can swap-remove out of `data`
+/// without disturbing any other element's `Index`.
+///
+/// This trades an extra indirection on `get`/`get_mut` for an `iter`
+/// that walks `data` directly, with no holes to skip and no cold cache
+/// lines from long-dead slots.
+pub struct DenseIndexedVec<T> {
+    // The live elements, packed with no holes.
+    data: Vec<T>,
+
+    // data[i] belongs to the slot data_to_slot[i].
+    data_to_slot: Vec<usize>,
+
+    // slot_to_data[slot] is the current position of that slot's element
+    // in `data`.  Meaningless for slots that are in `open`.
+    slot_to_data: Vec<usize>,
+
+    // The generation of each slot, bumped every time it's freed.
+    generations: Vec<u32>,
+
+    // The instance ID.  Used to tell if an index came from this vec.
+    instance: usize,
+
+    // Slots that have been freed by `take`/`remove` and are available
+    // to be handed back out by `add`.
+    open: BitSet,
+}
+
+impl <T> DenseIndexedVec<T> {
+    /// Creates a new, empty DenseIndexedVec.
+    pub fn new() -> DenseIndexedVec<T> {
+        DenseIndexedVec::with_capacity(16)
+    }
+
+    pub fn with_capacity(capacity: usize) -> DenseIndexedVec<T> {
+        let instance = INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
+        DenseIndexedVec {
+            data: Vec::with_capacity(capacity),
+            data_to_slot: Vec::with_capacity(capacity),
+            slot_to_data: Vec::with_capacity(capacity),
+            generations: Vec::with_capacity(capacity),
+            instance: instance,
+            open: BitSet::new(),
+        }
+    }
+
+    // Allocates a brand new slot for `value`, never reusing a hole.
+    fn do_push(&mut self, value: T) -> Index {
+        let slot = self.slot_to_data.len();
+        let data_pos = self.data.len();
+
+        self.data.push(value);
+        self.data_to_slot.push(slot);
+        self.slot_to_data.push(data_pos);
+        self.generations.push(0);
+
+        Index(self.instance, slot, 0)
+    }
+
+    // Fills an open slot with `value`, if one exists.
+    fn do_fill(&mut self, value: T) -> Result<Index, T> {
+        let hole = self.open.iter().nth(0);
+        if let Some(slot) = hole {
+            self.open.remove(&slot);
+
+            let data_pos = self.data.len();
+            self.data.push(value);
+            self.data_to_slot.push(slot);
+            self.slot_to_data[slot] = data_pos;
+
+            Ok(Index(self.instance, slot, self.generations[slot]))
+        } else {
+            Err(value)
+        }
+    }
+
+    fn assert_instance(&self, ins: usize, slot: usize, gen: u32) {
+        if ins != self.instance {
+            panic!("get() called with index that wasn't generated by
+                    this DenseIndexedVec.");
+        }
+        if gen != self.generations[slot] {
+            panic!("get() called with a stale index whose slot has since \
+                    been recycled.");
+        }
+    }
+
+    fn is_current(&self, ins: usize, slot: usize, gen: u32) -> bool {
+        ins == self.instance && gen == self.generations[slot]
+    }
+
+    /// Adds an element to the DenseIndexedVec.
+    ///
+    /// This function prefers to fill up holes left by removed items.
+    pub fn add(&mut self, value: T) -> Index {
+        match self.do_fill(value) {
+            Ok(i) => i,
+            Err(v) => self.do_push(v),
+        }
+    }
+
+    /// Adds an element to the DenseIndexedVec, always allocating a new
+    /// slot rather than reusing one left open by a removed item.
+    pub fn push(&mut self, value: T) -> Index {
+        self.do_push(value)
+    }
+
+    /// Returns a reference to an element in the vec.
+    pub fn get(&self, index: &Index) -> &T {
+        let &Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+        &self.data[self.slot_to_data[slot]]
+    }
+
+    /// Returns a mutable reference to an element in the vec.
+    pub fn get_mut(&mut self, index: &mut Index) -> &mut T {
+        let &mut Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+        let pos = self.slot_to_data[slot];
+        &mut self.data[pos]
+    }
+
+    /// Returns a reference to an element in the vec, or `None` if `index`
+    /// wasn't generated by this vec or its slot has since been recycled.
+    pub fn try_get(&self, index: &Index) -> Option<&T> {
+        let &Index(ins, slot, gen) = index;
+        if self.is_current(ins, slot, gen) {
+            Some(&self.data[self.slot_to_data[slot]])
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to an element in the vec, or `None` if
+    /// `index` wasn't generated by this vec or its slot has since been
+    /// recycled.
+    pub fn try_get_mut(&mut self, index: &mut Index) -> Option<&mut T> {
+        let &mut Index(ins, slot, gen) = index;
+        if self.is_current(ins, slot, gen) {
+            let pos = self.slot_to_data[slot];
+            Some(&mut self.data[pos])
+        } else {
+            None
+        }
+    }
+
+    /// Removes the element stored at Index location, returning it.
+    ///
+    /// This swap-removes the element out of the packed `data` array and
+    /// patches up whichever element used to be last, so `data` never
+    /// develops a hole.
+    pub fn take(&mut self, index: Index) -> T {
+        let Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+
+        let data_pos = self.slot_to_data[slot];
+        let last_pos = self.data.len() - 1;
+
+        if data_pos != last_pos {
+            self.data.swap(data_pos, last_pos);
+            self.data_to_slot.swap(data_pos, last_pos);
+            let moved_slot = self.data_to_slot[data_pos];
+            self.slot_to_data[moved_slot] = data_pos;
+        }
+
+        let out = self.data.pop().unwrap();
+        self.data_to_slot.pop();
+
+        self.open.insert(slot);
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+
+        out
+    }
+
+    /// Removes the element stored at Index location, dropping it.
+    pub fn remove(&mut self, index: Index) {
+        self.take(index);
+    }
+
+    /// Returns the number of live elements.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Iterates over the live elements directly, in packed order, with
+    /// no holes to skip.
+    pub fn iter(&self) -> slice::Iter<T> {
+        self.data.iter()
+    }
+
+    /// Iterates mutably over the live elements directly, in packed
+    /// order, with no holes to skip.
+    pub fn iter_mut(&mut self) -> slice::IterMut<T> {
+        self.data.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DenseIndexedVec;
+
+    #[test]
+    fn take_from_the_middle_patches_the_moved_element_index() {
+        let mut v = DenseIndexedVec::new();
+        let a = v.add(10);
+        let b = v.add(20);
+        let c = v.add(30);
+
+        // `c` is currently the last element in `data`; removing `a`
+        // swap-removes `c` into `a`'s old slot in `data`, so `b` and
+        // `c`'s indices both need to keep resolving to the right value.
+        assert_eq!(v.take(a), 10);
+
+        assert_eq!(*v.get(&b), 20);
+        assert_eq!(*v.get(&c), 30);
+    }
+
+    #[test]
+    fn iter_stays_packed_with_no_holes_after_removal() {
+        let mut v = DenseIndexedVec::new();
+        v.add(1);
+        let b = v.add(2);
+        v.add(3);
+
+        v.take(b);
+
+        let mut items: Vec<i32> = v.iter().cloned().collect();
+        items.sort();
+        assert_eq!(items, vec![1, 3]);
+        assert_eq!(v.len(), 2);
+    }
+
+    #[test]
+    fn add_can_refill_a_hole_left_by_take() {
+        let mut v = DenseIndexedVec::new();
+        let a = v.add(1);
+        v.take(a);
+        let b = v.add(2);
+        assert_eq!(*v.get(&b), 2);
+        assert_eq!(v.len(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering;
+
+use super::{Index, INSTANCE_ID};
+
+/// A `FixedIndexedVec` is an `IndexedVec` with its backing storage pinned
+/// to a compile-time capacity of `N` elements, the way `heapless::Vec`
+/// pins a `Vec` to a fixed capacity.
+///
+/// It never reallocates after construction -- there is no heap growth to
+/// pay for, which makes it usable in real-time code paths where a
+/// reallocation's latency spike is forbidden. The crate itself depends
+/// on `std`, so this type is not available on `no_std` targets. Once all `N` slots are
+/// occupied, `add`/`push` hand the value straight back instead of
+/// growing.
+pub struct FixedIndexedVec<T, const N: usize> {
+    // The backing storage. Slots at or past `len`, or present in `open`,
+    // hold logically uninitialized memory and must never be read or
+    // dropped.
+    mem: [MaybeUninit<T>; N],
+
+    // The generation of each slot, bumped every time it's freed.
+    generations: [u32; N],
+
+    // A stack of freed slot indices, stored in open[..open_len].
+    open: [usize; N],
+    open_len: usize,
+
+    // The number of slots that have ever been handed out by growing
+    // past the end of the array (as opposed to being recycled).
+    len: usize,
+
+    // The instance ID.  Used to tell if an index came from this vec.
+    instance: usize,
+}
+
+impl <T, const N: usize> FixedIndexedVec<T, N> {
+    /// Creates a new, empty FixedIndexedVec with a fixed capacity of `N`
+    /// elements. No allocation occurs, now or ever.
+    pub fn new() -> FixedIndexedVec<T, N> {
+        let instance = INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
+        FixedIndexedVec {
+            // Safe: an array of `MaybeUninit<T>` needs no initialization,
+            // since `MaybeUninit` itself carries no such invariant.
+            mem: unsafe { MaybeUninit::uninit().assume_init() },
+            generations: [0; N],
+            open: [0; N],
+            open_len: 0,
+            len: 0,
+            instance: instance,
+        }
+    }
+
+    /// The fixed capacity of this vec. Always equal to `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    fn pop_open(&mut self) -> Option<usize> {
+        if self.open_len == 0 {
+            None
+        } else {
+            self.open_len -= 1;
+            Some(self.open[self.open_len])
+        }
+    }
+
+    fn push_new_slot(&mut self, value: T) -> Result<Index, T> {
+        if self.len == N {
+            return Err(value);
+        }
+        let slot = self.len;
+        self.len += 1;
+        self.mem[slot] = MaybeUninit::new(value);
+        self.generations[slot] = 0;
+        Ok(Index(self.instance, slot, 0))
+    }
+
+    fn fill_hole(&mut self, value: T) -> Result<Index, T> {
+        if let Some(slot) = self.pop_open() {
+            self.mem[slot] = MaybeUninit::new(value);
+            Ok(Index(self.instance, slot, self.generations[slot]))
+        } else {
+            Err(value)
+        }
+    }
+
+    fn assert_instance(&self, ins: usize, slot: usize, gen: u32) {
+        if ins != self.instance {
+            panic!("get() called with index that wasn't generated by
+                    this FixedIndexedVec.");
+        }
+        if gen != self.generations[slot] {
+            panic!("get() called with a stale index whose slot has since \
+                    been recycled.");
+        }
+    }
+
+    fn is_current(&self, ins: usize, slot: usize, gen: u32) -> bool {
+        ins == self.instance && gen == self.generations[slot]
+    }
+
+    fn is_open(&self, slot: usize) -> bool {
+        self.open[..self.open_len].iter().any(|&s| s == slot)
+    }
+
+    /// Adds an element to the vec, returning the value back if the vec
+    /// is already at capacity instead of growing.
+    ///
+    /// This function prefers to fill up holes left by removed items.
+    pub fn add(&mut self, value: T) -> Result<Index, T> {
+        match self.fill_hole(value) {
+            Ok(i) => Ok(i),
+            Err(v) => self.push_new_slot(v),
+        }
+    }
+
+    /// Adds an element to the vec, returning the value back if the vec
+    /// is already at capacity instead of growing.
+    ///
+    /// This function prefers to add elements to the 'end' of the array
+    /// before filling holes.
+    pub fn push(&mut self, value: T) -> Result<Index, T> {
+        match self.push_new_slot(value) {
+            Ok(i) => Ok(i),
+            Err(v) => self.fill_hole(v),
+        }
+    }
+
+    /// Returns a reference to an element in the vec.
+    pub fn get(&self, index: &Index) -> &T {
+        let &Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+        unsafe { self.mem[slot].assume_init_ref() }
+    }
+
+    /// Returns a mutable reference to an element in the vec.
+    pub fn get_mut(&mut self, index: &mut Index) -> &mut T {
+        let &mut Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+        unsafe { self.mem[slot].assume_init_mut() }
+    }
+
+    /// Returns a reference to an element in the vec, or `None` if `index`
+    /// wasn't generated by this vec or its slot has since been recycled.
+    pub fn try_get(&self, index: &Index) -> Option<&T> {
+        let &Index(ins, slot, gen) = index;
+        if self.is_current(ins, slot, gen) {
+            Some(unsafe { self.mem[slot].assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to an element in the vec, or `None` if
+    /// `index` wasn't generated by this vec or its slot has since been
+    /// recycled.
+    pub fn try_get_mut(&mut self, index: &mut Index) -> Option<&mut T> {
+        let &mut Index(ins, slot, gen) = index;
+        if self.is_current(ins, slot, gen) {
+            Some(unsafe { self.mem[slot].assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Removes the element stored at Index location, returning it.
+    pub fn take(&mut self, index: Index) -> T {
+        let Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+
+        let out = unsafe { self.mem[slot].assume_init_read() };
+
+        self.open[self.open_len] = slot;
+        self.open_len += 1;
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+
+        out
+    }
+
+    /// Removes the element stored at Index location, dropping it.
+    pub fn remove(&mut self, index: Index) {
+        self.take(index);
+    }
+}
+
+impl <T, const N: usize> Drop for FixedIndexedVec<T, N> {
+    fn drop(&mut self) {
+        for slot in 0..self.len {
+            if !self.is_open(slot) {
+                unsafe { self.mem[slot].assume_init_drop() };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedIndexedVec;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn push_past_capacity_hands_the_value_back() {
+        let mut v: FixedIndexedVec<i32, 2> = FixedIndexedVec::new();
+        assert!(v.push(1).is_ok());
+        assert!(v.push(2).is_ok());
+        match v.push(3) {
+            Err(value) => assert_eq!(value, 3),
+            Ok(_) => panic!("push should have failed once the vec is at capacity"),
+        }
+    }
+
+    #[test]
+    fn add_refills_a_hole_without_growing_past_capacity() {
+        let mut v: FixedIndexedVec<i32, 2> = FixedIndexedVec::new();
+        let a = v.add(1).unwrap();
+        v.add(2).unwrap();
+        v.take(a);
+
+        let c = v.add(3).unwrap();
+        assert_eq!(*v.get(&c), 3);
+    }
+
+    #[test]
+    fn dropping_the_vec_drops_every_occupied_slot_exactly_once() {
+        let counter = Rc::new(Cell::new(0));
+        let mut v: FixedIndexedVec<DropCounter, 4> = FixedIndexedVec::new();
+
+        let a = v.add(DropCounter(counter.clone())).unwrap();
+        v.add(DropCounter(counter.clone())).unwrap();
+        v.add(DropCounter(counter.clone())).unwrap();
+
+        drop(v.take(a));
+        assert_eq!(counter.get(), 1);
+
+        drop(v);
+        assert_eq!(counter.get(), 3, "the two remaining slots should each drop exactly once");
+
+        #[derive(Debug)]
+        struct DropCounter(Rc<Cell<u32>>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+    }
+}
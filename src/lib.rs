@@ -1,13 +1,23 @@
-#![feature(collections)]
-
 use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
-use std::collections::BitSet;
-use std::mem::{forget, swap, transmute, zeroed};
+use std::collections::TryReserveError;
+use std::mem::{swap, transmute, MaybeUninit};
+
+mod bitset;
+use bitset::BitSet;
+
+mod dense;
+pub use dense::DenseIndexedVec;
+
+mod fixed;
+pub use fixed::FixedIndexedVec;
+
+mod null;
+pub use null::NullIndexedVec;
 
 static INSTANCE_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
-// (instance, index)
-pub struct Index(usize, usize);
+// (instance, index, generation)
+pub struct Index(usize, usize, u32);
 
 /// IndexedVec is a vector with a unique approach to indices.
 /// Once an item is added to the IndexedVec, a _unique_ index is returned.
@@ -15,17 +25,13 @@ pub struct Index(usize, usize);
 /// is garunteed by the rust typesystem for there only to be one of these
 /// indices at one point in time.
 ///
-/// This means that we can perform operations that would otherwise be unsafe in
-/// a perfectly safe maner.  For example, you can grab a mutable reference
-/// to an element from an immutable IndexedVec.  This is hugely useful in
-/// multithreaded environments.
-///
 /// Because the Index is garunteed to exist and point to a valid location in
 /// the backing array, the implementation of IndexedVec can also do lookups
 /// without bounds checking.
 pub struct IndexedVec<T> {
-    // The backing vector
-    mem: Vec<T>,
+    // The backing vector.  Slots that are in `open` hold logically
+    // uninitialized memory and must never be read or dropped.
+    mem: Vec<MaybeUninit<T>>,
 
     // The instance ID.  Used to tell if an index came from this IndexedVec
     instance: usize,
@@ -33,10 +39,25 @@ pub struct IndexedVec<T> {
     // A set of positions that are open.  An index can become open
     // if an item is removed from that location in the array.
     open: BitSet,
+
+    // The current generation of each slot, bumped every time a slot is
+    // freed by `take`/`remove`.  An `Index` only points at a live element
+    // if its stored generation matches the slot's current generation.
+    //
+    // NOTE: `Index` is move-only today, so the borrow checker alone
+    // already prevents two live `Index` values from ever pointing at
+    // the same slot; there is currently no safe-API path that produces
+    // a stale `Index` for this check to catch. It's kept as a deliberate
+    // head start for the day `Index` needs to be duplicated (handed to
+    // multiple threads, stashed in more than one place, etc.) -- at
+    // that point this is the mechanism that makes a stale copy a
+    // detectable error instead of silent aliasing, rather than
+    // something bolted on after the fact.
+    generations: Vec<u32>,
 }
 
 impl <T> IndexedVec<T> {
-    /// Creates a new BoundedArray with a given size.
+    /// Creates a new IndexedVec with a default capacity of 16.
     pub fn new() -> IndexedVec<T> {
         IndexedVec::with_capacity(16)
     }
@@ -46,14 +67,16 @@ impl <T> IndexedVec<T> {
         IndexedVec {
             mem: Vec::with_capacity(capacity),
             instance: instance,
-            open: BitSet::new()
+            open: BitSet::new(),
+            generations: Vec::with_capacity(capacity),
         }
     }
 
     fn do_push(&mut self, value: T) -> Index {
         let len = self.mem.len();
-        self.mem.push(value);
-        Index(self.instance, len)
+        self.mem.push(MaybeUninit::new(value));
+        self.generations.push(0);
+        Index(self.instance, len, 0)
     }
 
     fn do_fill(&mut self, value: T) -> Result<Index, T> {
@@ -61,119 +84,192 @@ impl <T> IndexedVec<T> {
         if let Some(h) = hole {
             self.open.remove(&h);
             let arr = &mut self.mem[..];
-            let mut val = value;
             unsafe {
                 // This is safe because the only way that
                 // `h` could get into the open set is by
-                // being a valid index and being removed.
-                swap(&mut val, arr.get_unchecked_mut(h));
-
-                // This is safe because when `h` got pushed
-                // into the open set, the contents were zeroed
-                // so this value can not be destrucuted.
-                forget(val);
+                // being a valid index and being removed, which
+                // leaves the slot logically uninitialized.  We
+                // simply overwrite it rather than dropping the
+                // old (nonexistent) contents.
+                *arr.get_unchecked_mut(h) = MaybeUninit::new(value);
             }
-            Ok(Index(self.instance, h))
+            // `take`/`remove` already bumped this slot's generation
+            // when it was freed, so the new Index just inherits it.
+            Ok(Index(self.instance, h, self.generations[h]))
         } else {
             Err(value)
         }
     }
 
-    fn assert_instance(&self, i: usize) {
-        if i != self.instance {
-            panic!("get() called with index that wasn't generated by the
-                    this BoundedArray.");
+    fn assert_instance(&self, ins: usize, i: usize, gen: u32) {
+        if ins != self.instance {
+            panic!("get() called with index that wasn't generated by
+                    this IndexedVec.");
         }
+        if gen != self.generations[i] {
+            panic!("get() called with a stale index whose slot has since \
+                    been recycled.");
+        }
+    }
+
+    /// Returns `true` if `(ins, i, gen)` still points at the element it was
+    /// created for, i.e. the slot hasn't been freed by a `take`/`remove`
+    /// since this index was handed out.
+    fn is_current(&self, ins: usize, i: usize, gen: u32) -> bool {
+        ins == self.instance && gen == self.generations[i]
     }
 
-    /// Adds an element to the BoundedVec.
+    /// Adds an element to the IndexedVec.
     ///
     /// This function prefers to fill up holes in the array
     /// left by removing other items.
+    ///
+    /// Panics on allocation failure; see `try_add` for a fallible version.
     pub fn add(&mut self, value: T) -> Index {
+        match self.try_add(value) {
+            Ok(i) => i,
+            Err((_, e)) => panic!("IndexedVec::add: allocation failed: {:?}", e),
+        }
+    }
+
+    /// Like `add`, but returns the value back along with the allocation
+    /// error instead of aborting the process when growing the backing
+    /// storage fails.
+    pub fn try_add(&mut self, value: T) -> Result<Index, (T, TryReserveError)> {
         let value = match self.do_fill(value) {
-            Ok(i) => return i,
+            Ok(i) => return Ok(i),
             Err(v) => v
         };
 
         let len = self.mem.len();
         if len == self.mem.capacity() {
-            self.mem.reserve(len / 3);
+            if let Err(e) = self.mem.try_reserve(len / 3 + 1) {
+                return Err((value, e));
+            }
+        }
+        // `do_push` grows `generations` in lockstep with `mem`, so it
+        // needs the same fallible reservation -- otherwise it falls
+        // through to `Vec`'s ordinary infallible growth and can abort
+        // on the exact allocation failure `try_add` exists to avoid.
+        if len == self.generations.capacity() {
+            if let Err(e) = self.generations.try_reserve(len / 3 + 1) {
+                return Err((value, e));
+            }
         }
 
-        self.do_push(value)
+        Ok(self.do_push(value))
     }
 
-    /// Adds an element to the BoundedVec.
+    /// Adds an element to the IndexedVec.
     ///
     /// This function prefers to add elements to the 'end' of the array
     /// before filling holes. It will fill holes if otherwise a resize
     /// would be required.
+    ///
+    /// Panics on allocation failure; see `try_push` for a fallible version.
     pub fn push(&mut self, value: T) -> Index {
-        if self.mem.len() != self.mem.capacity() {
-            self.do_push(value)
+        match self.try_push(value) {
+            Ok(i) => i,
+            Err((_, e)) => panic!("IndexedVec::push: allocation failed: {:?}", e),
+        }
+    }
+
+    /// Like `push`, but returns the value back along with the allocation
+    /// error instead of aborting the process when growing the backing
+    /// storage fails.
+    pub fn try_push(&mut self, value: T) -> Result<Index, (T, TryReserveError)> {
+        if self.mem.len() != self.mem.capacity()
+            && self.generations.len() != self.generations.capacity() {
+            Ok(self.do_push(value))
         } else {
-            self.add(value)
+            self.try_add(value)
         }
     }
 
     /// Returns a reference to an element in the array.
+    ///
+    /// Panics if `index` wasn't generated by this IndexedVec, or if its
+    /// slot has since been recycled by a `take`/`remove` and a new `add`.
     pub fn get<'a, 'b, 'c: 'a + 'b>(&'a self, index: &'b Index) -> &'c T {
-        let &Index(ins, i) = index;
-        self.assert_instance(ins);
+        let &Index(ins, i, gen) = index;
+        self.assert_instance(ins, i, gen);
 
-        let arr: &'a [T] = &self.mem[..];
+        let arr: &'a [MaybeUninit<T>] = &self.mem[..];
 
         unsafe {
             // Safe because we are increasing the lifetime, not decreasing it.
             transmute(
                 // Safe because we know that this index is
-                // occupied (beacause we generated it).
-                arr.get_unchecked(i))
+                // occupied (beacause we generated it), so the
+                // slot is fully initialized.
+                arr.get_unchecked(i).assume_init_ref())
         }
     }
 
     /// Returns a mutable reference to an element in the array.
-    pub fn get_mut<'a, 'b, 'c: 'a + 'b>(&'a self, index: &'b mut Index) -> &'c mut T {
-        let &mut Index(ins, i) = index;
-        self.assert_instance(ins);
+    ///
+    /// Panics if `index` wasn't generated by this IndexedVec, or if its
+    /// slot has since been recycled by a `take`/`remove` and a new `add`.
+    pub fn get_mut(&mut self, index: &mut Index) -> &mut T {
+        let &mut Index(ins, i, gen) = index;
+        self.assert_instance(ins, i, gen);
 
         unsafe {
-            // Safe because we are only accessing the location for which
-            // we are the only one that can actually access it.
-            let arr: &mut [T] = transmute(&self.mem[..]);
+            // Safe because we know that this index is
+            // occupied (beacause we generated it), so the
+            // slot is fully initialized.
+            self.mem.get_unchecked_mut(i).assume_init_mut()
+        }
+    }
 
-            // Safe because we are just using this to increase the lifetime
-            // bound from 'b, to 'c, not.
-            transmute(
-                // Safe because we know that this index is
-                // occupied (beacause we generated it).
-                arr.get_unchecked_mut(i))
+    /// Returns a reference to an element in the array, or `None` if
+    /// `index` wasn't generated by this IndexedVec or its slot has since
+    /// been recycled, instead of panicking like `get`.
+    pub fn try_get<'a, 'b, 'c: 'a + 'b>(&'a self, index: &'b Index) -> Option<&'c T> {
+        let &Index(ins, i, gen) = index;
+        if self.is_current(ins, i, gen) {
+            Some(self.get(index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to an element in the array, or `None`
+    /// if `index` wasn't generated by this IndexedVec or its slot has
+    /// since been recycled, instead of panicking like `get_mut`.
+    pub fn try_get_mut(&mut self, index: &mut Index) -> Option<&mut T> {
+        let &mut Index(ins, i, gen) = index;
+        if self.is_current(ins, i, gen) {
+            Some(self.get_mut(index))
+        } else {
+            None
         }
     }
 
     /// Swaps the element at an index, returning the previous value.
-    pub fn swap(&self, index: &mut Index, mut value: T) -> T {
-        self.assert_instance(index.0);
+    pub fn swap(&mut self, index: &mut Index, mut value: T) -> T {
+        self.assert_instance(index.0, index.1, index.2);
         swap(self.get_mut(index), &mut value);
         value
     }
 
     /// Remove the element stored at Index location, returning it.
     pub fn take(&mut self, index: Index) -> T {
-        let Index(ins, i) = index;
-        self.assert_instance(ins);
-
-        let mut copy = Index(ins, i);
+        let Index(ins, i, gen) = index;
+        self.assert_instance(ins, i, gen);
 
-        let mut out = unsafe { zeroed() };
-
-        {
-            let inside = self.get_mut(&mut copy);
-            swap(&mut out, inside);
-        }
+        let out = unsafe {
+            // Safe because we know that this index is occupied
+            // (beacause we generated it), so the slot is fully
+            // initialized.  After this read the slot is logically
+            // uninitialized, which is why we immediately mark it open.
+            self.mem.get_unchecked(i).assume_init_read()
+        };
 
         self.open.insert(i);
+        // Bump the generation so that any other Index still holding
+        // `(instance, i, gen)` is now recognized as stale.
+        self.generations[i] = self.generations[i].wrapping_add(1);
 
         out
     }
@@ -186,12 +282,94 @@ impl <T> IndexedVec<T> {
 
 impl <T> Drop for IndexedVec<T> {
     fn drop(&mut self) {
-        for (i, v) in self.mem.drain().enumerate() {
+        for (i, v) in self.mem.drain(..).enumerate() {
             if self.open.contains(&i) {
-                unsafe{ forget(v); }
+                // Logically uninitialized: nothing to drop.
             } else {
-                drop(v);
+                unsafe { v.assume_init() };
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedVec;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn works_with_a_non_zeroable_t() {
+        // `Box` has no all-zero bit pattern, so this would have been
+        // instant UB under the old `zeroed()`/`forget` hole machinery.
+        let mut v = IndexedVec::new();
+        let i = v.add(Box::new(42));
+        assert_eq!(**v.get(&i), 42);
+    }
+
+    #[test]
+    fn take_returns_the_value_and_leaves_no_double_drop() {
+        let counter = Rc::new(Cell::new(0));
+        let mut v = IndexedVec::new();
+
+        let a = v.add(DropCounter(counter.clone()));
+        let b = v.add(DropCounter(counter.clone()));
+
+        drop(v.take(a));
+        assert_eq!(counter.get(), 1);
+
+        drop(v);
+        assert_eq!(counter.get(), 2, "the remaining element should still drop once");
+
+        struct DropCounter(Rc<Cell<u32>>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn dropping_the_vec_drops_every_occupied_slot_exactly_once() {
+        let counter = Rc::new(Cell::new(0));
+        let mut v = IndexedVec::new();
+
+        for _ in 0..8 {
+            v.add(DropCounter(counter.clone()));
+        }
+
+        drop(v);
+        assert_eq!(counter.get(), 8);
+
+        struct DropCounter(Rc<Cell<u32>>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn get_mut_and_swap_observe_each_others_writes() {
+        let mut v = IndexedVec::new();
+        let mut i = v.add(1);
+
+        *v.get_mut(&mut i) += 1;
+        assert_eq!(*v.get(&i), 2);
+
+        let old = v.swap(&mut i, 10);
+        assert_eq!(old, 2);
+        assert_eq!(*v.get(&i), 10);
+    }
+
+    #[test]
+    fn add_refills_a_hole_left_by_take() {
+        let mut v = IndexedVec::new();
+        let a = v.add(1);
+        let b = v.add(2);
+        v.take(a);
+        let c = v.add(3);
+        assert_eq!(*v.get(&b), 2);
+        assert_eq!(*v.get(&c), 3);
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::atomic::Ordering;
+
+use super::bitset::BitSet;
+use super::{Index, INSTANCE_ID};
+
+/// A sibling of `IndexedVec` for zero-sized `T`, modeled on specs'
+/// `NullStorage`.
+///
+/// When every value of `T` is indistinguishable (as is the case for any
+/// ZST), there is nothing useful to actually store per-slot -- so
+/// `NullIndexedVec` doesn't keep a backing `Vec<T>` at all.  Presence is
+/// tracked purely through a `BitSet` of open (freed) slots, and `get`
+/// simply hands back a reference to one canonical `T` shared by every
+/// occupied slot. This makes `NullIndexedVec` an efficient slab of
+/// boolean flags keyed by stable, generation-checked indices -- handy
+/// for tagging entities without paying for per-element storage.
+pub struct NullIndexedVec<T> {
+    // The single canonical instance returned by every `get`. Since `T`
+    // is zero-sized, every value of `T` is interchangeable with this one.
+    canonical: T,
+
+    // The generation of each slot, bumped every time it's freed.
+    generations: Vec<u32>,
+
+    // The number of slots that have ever been handed out.
+    len: usize,
+
+    // The instance ID.  Used to tell if an index came from this vec.
+    instance: usize,
+
+    // A set of positions that are open.  An index can become open
+    // if an item is removed from that location.
+    open: BitSet,
+}
+
+impl <T: Default> NullIndexedVec<T> {
+    // Forces a compile error if `T` isn't zero-sized. This is only
+    // actually evaluated because `new()` references it below -- an
+    // unreferenced generic assoc const is never checked, so the
+    // reference is load-bearing, not decoration.
+    const ASSERT_T_IS_ZERO_SIZED: () = assert!(
+        std::mem::size_of::<T>() == 0,
+        "NullIndexedVec<T> requires T to be zero-sized: it only tracks \
+         presence via a BitSet and would otherwise silently discard the \
+         value passed to `add`"
+    );
+
+    /// Creates a new, empty NullIndexedVec.
+    ///
+    /// Fails to compile if `T` is not zero-sized -- see the type's docs
+    /// for why a non-ZST `T` can't be supported here.
+    ///
+    /// ```compile_fail
+    /// use indexed_vec::NullIndexedVec;
+    /// let _v: NullIndexedVec<i32> = NullIndexedVec::new();
+    /// ```
+    pub fn new() -> NullIndexedVec<T> {
+        let () = Self::ASSERT_T_IS_ZERO_SIZED;
+
+        let instance = INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
+        NullIndexedVec {
+            canonical: T::default(),
+            generations: Vec::new(),
+            len: 0,
+            instance: instance,
+            open: BitSet::new(),
+        }
+    }
+
+    fn assert_instance(&self, ins: usize, slot: usize, gen: u32) {
+        if ins != self.instance {
+            panic!("get() called with index that wasn't generated by
+                    this NullIndexedVec.");
+        }
+        if gen != self.generations[slot] {
+            panic!("get() called with a stale index whose slot has since \
+                    been recycled.");
+        }
+    }
+
+    /// Marks a slot as present, returning an Index for it.
+    ///
+    /// The passed-in `value` is discarded immediately -- since `T` is
+    /// zero-sized there's nothing to keep, and `get` always hands back
+    /// the canonical instance instead.
+    pub fn add(&mut self, value: T) -> Index {
+        drop(value);
+
+        let hole = self.open.iter().nth(0);
+        if let Some(slot) = hole {
+            self.open.remove(&slot);
+            Index(self.instance, slot, self.generations[slot])
+        } else {
+            let slot = self.len;
+            self.len += 1;
+            self.generations.push(0);
+            Index(self.instance, slot, 0)
+        }
+    }
+
+    /// Marks a slot as present, returning an Index for it. Equivalent to
+    /// `add`, kept for parity with `IndexedVec::push`.
+    pub fn push(&mut self, value: T) -> Index {
+        self.add(value)
+    }
+
+    /// Returns a reference to the canonical `T` instance, provided
+    /// `index` is still marked present.
+    pub fn get(&self, index: &Index) -> &T {
+        let &Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+        &self.canonical
+    }
+
+    /// Returns `true` if `index` is still marked present in this vec.
+    pub fn contains(&self, index: &Index) -> bool {
+        let &Index(ins, slot, gen) = index;
+        ins == self.instance && gen == self.generations[slot]
+    }
+
+    /// Clears the slot at Index location, returning a fresh `T`.
+    pub fn take(&mut self, index: Index) -> T {
+        let Index(ins, slot, gen) = index;
+        self.assert_instance(ins, slot, gen);
+
+        self.open.insert(slot);
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+
+        T::default()
+    }
+
+    /// Clears the slot at Index location.
+    pub fn remove(&mut self, index: Index) {
+        self.take(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NullIndexedVec;
+
+    #[test]
+    fn add_marks_present_and_get_returns_the_canonical_instance() {
+        let mut v: NullIndexedVec<()> = NullIndexedVec::new();
+        let a = v.add(());
+        assert!(v.contains(&a));
+        assert_eq!(*v.get(&a), ());
+    }
+
+    #[test]
+    fn take_clears_the_slot_and_add_can_refill_it() {
+        let mut v: NullIndexedVec<()> = NullIndexedVec::new();
+        let a = v.add(());
+        v.take(a);
+
+        let b = v.add(());
+        assert!(v.contains(&b));
+    }
+}